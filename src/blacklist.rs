@@ -0,0 +1,190 @@
+//! Domain/IP blocklist: loads a newline-delimited rules file and answers
+//! matching queries with a synthesized response instead of the real one.
+//!
+//! A line in the rules file is one of:
+//!   - `0.0.0.0` / `::1` / any parseable IP — blocks an answer IP
+//!   - `=exact.example.com` — blocks only that exact name
+//!   - `*.example.com` — blocks any subdomain of `example.com`, not the name itself
+//!   - `ads.example.com` (bare) — blocks that name and every subdomain of it
+//!
+//! Suffix and wildcard rules are stored in a trie keyed by reversed labels
+//! (TLD first), so a match is found in O(labels) regardless of list size.
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::RwLock;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::dns_over_tls::record_blocked_query;
+
+#[derive(Default)]
+struct TrieNode {
+    children: HashMap<String, TrieNode>,
+    /// Set by an exact-via-suffix (bare) rule: this name itself is blocked.
+    blocks_here: bool,
+    /// Set by a wildcard or bare-suffix rule: every name strictly under
+    /// this one is blocked.
+    blocks_descendants: bool,
+}
+
+impl TrieNode {
+    fn insert(&mut self, labels: &[String], blocks_here: bool, blocks_descendants: bool) {
+        match labels.split_last() {
+            None => {
+                self.blocks_here |= blocks_here;
+                self.blocks_descendants |= blocks_descendants;
+            }
+            Some((label, rest)) => {
+                self.children
+                    .entry(label.clone())
+                    .or_default()
+                    .insert(rest, blocks_here, blocks_descendants);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Blacklist {
+    exact: std::collections::HashSet<String>,
+    trie: TrieNode,
+    blocked_ips: std::collections::HashSet<IpAddr>,
+}
+
+impl Blacklist {
+    fn load_from_file(path: &str) -> Result<Blacklist, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut blacklist = Blacklist::default();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Ok(ip) = line.parse::<IpAddr>() {
+                blacklist.blocked_ips.insert(ip);
+            } else if let Some(name) = line.strip_prefix('=') {
+                blacklist.exact.insert(normalize(name));
+            } else if let Some(name) = line.strip_prefix("*.") {
+                let labels = reversed_labels(name);
+                blacklist.trie.insert(&labels, false, true);
+            } else {
+                let labels = reversed_labels(line);
+                blacklist.trie.insert(&labels, true, true);
+            }
+        }
+
+        Ok(blacklist)
+    }
+
+    fn is_domain_blocked(&self, domain: &str) -> bool {
+        let domain = normalize(domain);
+        if self.exact.contains(&domain) {
+            return true;
+        }
+
+        let labels: Vec<&str> = domain.split('.').filter(|l| !l.is_empty()).collect();
+        let mut node = &self.trie;
+        for (i, label) in labels.iter().rev().enumerate() {
+            let is_last = i == labels.len() - 1;
+            match node.children.get(*label) {
+                Some(child) => {
+                    node = child;
+                    if !is_last && node.blocks_descendants {
+                        return true;
+                    }
+                }
+                None => return false,
+            }
+        }
+        node.blocks_here
+    }
+
+    fn is_ip_blocked(&self, ip: &IpAddr) -> bool {
+        self.blocked_ips.contains(ip)
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn reversed_labels(domain: &str) -> Vec<String> {
+    normalize(domain)
+        .split('.')
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// What to answer with when a query is blocked.
+#[derive(Clone, Copy)]
+pub enum BlockAction {
+    NxDomain,
+    Sinkhole,
+}
+
+/// Read the configured block action from `DNS_BLACKLIST_ACTION`
+/// (`"nxdomain"` or `"sinkhole"`), defaulting to sinkhole.
+pub fn block_action() -> BlockAction {
+    match std::env::var("DNS_BLACKLIST_ACTION").ok().as_deref() {
+        Some("nxdomain") => BlockAction::NxDomain,
+        _ => BlockAction::Sinkhole,
+    }
+}
+
+/// The fixed sinkhole address to answer a blocked query with.
+pub fn sinkhole_ip(wants_ipv6: bool) -> IpAddr {
+    if wants_ipv6 {
+        IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    }
+}
+
+static BLACKLIST: Lazy<RwLock<Blacklist>> = Lazy::new(|| RwLock::new(load_blacklist()));
+
+fn load_blacklist() -> Blacklist {
+    let path = std::env::var("DNS_BLACKLIST_FILE").unwrap_or_else(|_| "blacklist.txt".to_string());
+    match Blacklist::load_from_file(&path) {
+        Ok(blacklist) => blacklist,
+        Err(e) => {
+            warn!(error = %e, %path, "Failed to load blacklist file, starting with an empty blacklist");
+            Blacklist::default()
+        }
+    }
+}
+
+/// Check `domain` against the blacklist, recording a hit in the shared
+/// metrics counter if it matches.
+pub fn is_domain_blocked(domain: &str) -> bool {
+    let blocked = BLACKLIST.read().unwrap().is_domain_blocked(domain);
+    if blocked {
+        record_blocked_query();
+    }
+    blocked
+}
+
+/// Check `ip` against the blacklist, recording a hit in the shared metrics
+/// counter if it matches.
+pub fn is_ip_blocked(ip: &IpAddr) -> bool {
+    let blocked = BLACKLIST.read().unwrap().is_ip_blocked(ip);
+    if blocked {
+        record_blocked_query();
+    }
+    blocked
+}
+
+/// Watch for SIGHUP and reload the blacklist file from disk each time it
+/// arrives, so an operator can refresh the rules without restarting.
+pub async fn run_blacklist_reloader() -> Result<(), Box<dyn std::error::Error>> {
+    let mut sighup = signal(SignalKind::hangup())?;
+    loop {
+        sighup.recv().await;
+        *BLACKLIST.write().unwrap() = load_blacklist();
+        info!("Reloaded blacklist from disk");
+    }
+}
@@ -8,7 +8,14 @@ pub fn serialize_resource_record(record: &dns_parser::ResourceRecord, buf: &mut
     // Serializar el tipo y clase
     let record_type: u16 = match &record.data {
         RData::A(_) => 1,       // Tipo A (IPv4)
+        RData::NS(_) => 2,      // Tipo NS
+        RData::CNAME(_) => 5,   // Tipo CNAME
+        RData::SOA(_) => 6,     // Tipo SOA
+        RData::PTR(_) => 12,    // Tipo PTR
+        RData::MX(_) => 15,     // Tipo MX
+        RData::TXT(_) => 16,    // Tipo TXT
         RData::AAAA(_) => 28,   // Tipo AAAA (IPv6)
+        RData::SRV(_) => 33,    // Tipo SRV
         _ => return Err("Tipo de registro no soportado".into()),
     };
     buf.write_all(&record_type.to_be_bytes())?;
@@ -24,6 +31,34 @@ pub fn serialize_resource_record(record: &dns_parser::ResourceRecord, buf: &mut
     match &record.data {
         RData::A(a) => buf.write_all(&a.0.octets())?,
         RData::AAAA(aaaa) => buf.write_all(&aaaa.0.octets())?,
+        RData::NS(ns) => serialize_name(&ns.0, buf)?,
+        RData::CNAME(cname) => serialize_name(&cname.0, buf)?,
+        RData::PTR(ptr) => serialize_name(&ptr.0, buf)?,
+        RData::MX(mx) => {
+            buf.write_all(&mx.preference.to_be_bytes())?;
+            serialize_name(&mx.exchange, buf)?;
+        }
+        RData::SOA(soa) => {
+            serialize_name(&soa.primary_ns, buf)?;
+            serialize_name(&soa.mailbox, buf)?;
+            buf.write_all(&soa.serial.to_be_bytes())?;
+            buf.write_all(&soa.refresh.to_be_bytes())?;
+            buf.write_all(&soa.retry.to_be_bytes())?;
+            buf.write_all(&soa.expire.to_be_bytes())?;
+            buf.write_all(&soa.minimum_ttl.to_be_bytes())?;
+        }
+        RData::TXT(txt) => {
+            for chunk in txt.iter() {
+                buf.push(chunk.len() as u8);
+                buf.write_all(chunk)?;
+            }
+        }
+        RData::SRV(srv) => {
+            buf.write_all(&srv.priority.to_be_bytes())?;
+            buf.write_all(&srv.weight.to_be_bytes())?;
+            buf.write_all(&srv.port.to_be_bytes())?;
+            serialize_name(&srv.target, buf)?;
+        }
         _ => return Err("Tipo de registro no soportado".into()),
     }
     let end_len = buf.len();
@@ -49,3 +84,26 @@ fn serialize_name(name: &dns_parser::Name, buf: &mut Vec<u8>) -> Result<(), Box<
     buf.push(0);
     Ok(())
 }
+
+/// Wire-encode a plain domain string into a standalone buffer.
+///
+/// The resolver hands back owned `String`s (NS targets, MX exchanges, SOA
+/// names...) that don't live inside a parsed `dns_parser::Packet`, so there's
+/// no buffer for `dns_parser::Name::scan` to borrow from. Encoding into an
+/// owned `Vec<u8>` first lets callers do `Name::scan(&buf, &buf)` to get a
+/// `Name` they can hand to `serialize_resource_record`.
+pub fn encode_name(domain: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut buf = Vec::new();
+    for label in domain.trim_end_matches('.').split('.') {
+        if !label.is_empty() {
+            let len = label.len();
+            if len > 63 {
+                return Err("Etiqueta demasiado larga".into());
+            }
+            buf.push(len as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+    Ok(buf)
+}
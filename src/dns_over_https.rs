@@ -0,0 +1,167 @@
+use dns_parser::{Packet, QueryType};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use tracing::{info, warn, error, instrument};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::resolver;
+use crate::dns_over_tls::{
+    build_dns_response, build_notimp_response, load_tls_config, cache_get, cache_put,
+    record_query_type, record_rate_limited, record_resolution_latency, record_total_query,
+    RATE_LIMITER,
+};
+use std::time::Instant;
+
+const DNS_MESSAGE_MEDIA_TYPE: &str = "application/dns-message";
+
+#[instrument]
+pub async fn run_doh_server(listener: TcpListener) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_tls_config(vec![b"h2".to_vec()])?;
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    info!("DNS-over-HTTPS server started on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, peer_addr) = listener.accept().await?;
+
+        if RATE_LIMITER.check_one().is_err() {
+            warn!(%peer_addr, "Rate limit reached");
+            record_rate_limited();
+            continue;
+        }
+
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            let tls_stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!(%peer_addr, error = %e, "TLS handshake failed");
+                    return;
+                }
+            };
+            info!(%peer_addr, "TLS connection established");
+
+            let service = service_fn(move |req| handle_doh_request(req, peer_addr));
+            if let Err(e) = Http::new().http2_only(true).serve_connection(tls_stream, service).await {
+                error!(%peer_addr, error = %e, "Error serving DoH connection");
+            }
+        });
+    }
+}
+
+async fn handle_doh_request(
+    req: Request<Body>,
+    peer_addr: SocketAddr,
+) -> Result<Response<Body>, Infallible> {
+    if req.uri().path() != "/dns-query" {
+        return Ok(status_response(StatusCode::NOT_FOUND));
+    }
+
+    let query = match *req.method() {
+        Method::POST => match hyper::body::to_bytes(req.into_body()).await {
+            Ok(bytes) => bytes.to_vec(),
+            Err(e) => {
+                warn!(%peer_addr, error = %e, "Failed to read DoH POST body");
+                return Ok(status_response(StatusCode::BAD_REQUEST));
+            }
+        },
+        Method::GET => match decode_get_query(&req) {
+            Some(bytes) => bytes,
+            None => return Ok(status_response(StatusCode::BAD_REQUEST)),
+        },
+        _ => return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED)),
+    };
+
+    let packet = match Packet::parse(&query) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!(%peer_addr, error = %e, "Failed to parse DoH query");
+            return Ok(status_response(StatusCode::BAD_REQUEST));
+        }
+    };
+    record_total_query();
+
+    let Some(question) = packet.questions.first() else {
+        return Ok(status_response(StatusCode::BAD_REQUEST));
+    };
+    let domain = question.qname.to_string();
+    let default_ttl = 60;
+    let cache_key = (domain.clone(), question.qtype as u16);
+    record_query_type(question.qtype);
+    let query_started = Instant::now();
+
+    if let Some((cached, ttl)) = cache_get(&cache_key) {
+        info!(%peer_addr, %domain, "Served DoH response from cache");
+        record_resolution_latency(query_started.elapsed());
+        return Ok(dns_message_response(cached, ttl));
+    }
+
+    let (response, ttl) = match question.qtype {
+        QueryType::A => {
+            let resolved = resolver::resolve_recursively(&domain);
+            let ad = resolver::answer_is_secure();
+            let (ip, ttl) = resolved.unwrap_or_else(|| ("192.168.1.1".parse().unwrap(), default_ttl));
+            info!(%peer_addr, %domain, ip = %ip, "Resolved DoH query");
+            match build_dns_response(&query, &question.qname, ip, ttl, ad) {
+                Ok(bytes) => (bytes, ttl),
+                Err(e) => {
+                    error!(%peer_addr, %domain, error = %e, "Failed to build DoH response");
+                    return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+                }
+            }
+        }
+        QueryType::AAAA => {
+            let resolved = resolver::resolve_aaaa(&domain);
+            let ad = resolver::answer_is_secure();
+            let (ip, ttl) = resolved.unwrap_or_else(|| ("::1".parse().unwrap(), default_ttl));
+            info!(%peer_addr, %domain, ip = %ip, "Resolved DoH query");
+            match build_dns_response(&query, &question.qname, ip, ttl, ad) {
+                Ok(bytes) => (bytes, ttl),
+                Err(e) => {
+                    error!(%peer_addr, %domain, error = %e, "Failed to build DoH response");
+                    return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+                }
+            }
+        }
+        other => {
+            warn!(%peer_addr, %domain, qtype = ?other, "Unsupported DoH query type, replying NOTIMP");
+            record_resolution_latency(query_started.elapsed());
+            return Ok(dns_message_response(build_notimp_response(&query), default_ttl));
+        }
+    };
+
+    record_resolution_latency(query_started.elapsed());
+    cache_put(cache_key, response.clone(), ttl);
+    Ok(dns_message_response(response, ttl))
+}
+
+/// Pull the base64url-encoded `dns` query parameter out of a DoH GET request.
+fn decode_get_query(req: &Request<Body>) -> Option<Vec<u8>> {
+    let query = req.uri().query()?;
+    let raw = query.split('&')
+        .find_map(|pair| pair.strip_prefix("dns="))?;
+    URL_SAFE_NO_PAD.decode(raw).ok()
+}
+
+fn dns_message_response(body: Vec<u8>, ttl: u32) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, DNS_MESSAGE_MEDIA_TYPE)
+        .header(CACHE_CONTROL, format!("max-age={}", ttl))
+        .body(Body::from(body))
+        .unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -8,40 +8,308 @@ use governor::{Quota, RateLimiter};
 use lru::LruCache;
 use once_cell::sync::Lazy;
 use tracing::{info, warn, error, instrument};
+use rand::Rng;
+use std::collections::HashMap;
 use std::net::IpAddr;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
 
 use crate::resolver;
-use crate::common::serialize_resource_record;
+use crate::blacklist::{self, BlockAction};
+use crate::common::{serialize_resource_record, encode_name};
+
+/// Upper bounds (milliseconds) for the resolution-latency histogram's
+/// buckets. Prometheus's cumulative `le` convention applies: each bucket
+/// counts every observation at or below its own bound, not just the ones
+/// between it and the previous bound.
+const LATENCY_BUCKETS_MS: [f64; 8] = [1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0];
+
+struct LatencyHistogram {
+    bucket_counts: Vec<AtomicUsize>,
+    sum_micros: AtomicU64,
+    count: AtomicUsize,
+}
+
+impl LatencyHistogram {
+    fn new() -> Self {
+        LatencyHistogram {
+            bucket_counts: LATENCY_BUCKETS_MS.iter().map(|_| AtomicUsize::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    fn record(&self, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        for (bound, counter) in LATENCY_BUCKETS_MS.iter().zip(&self.bucket_counts) {
+            if ms <= *bound {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencyHistogramSnapshot {
+        LatencyHistogramSnapshot {
+            buckets: LATENCY_BUCKETS_MS.iter().zip(&self.bucket_counts)
+                .map(|(bound, counter)| (*bound, counter.load(Ordering::Relaxed)))
+                .collect(),
+            sum_ms: self.sum_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            count: self.count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A point-in-time read of [`LatencyHistogram`], for [`crate::metrics`] to
+/// render as a Prometheus histogram.
+pub(crate) struct LatencyHistogramSnapshot {
+    pub buckets: Vec<(f64, usize)>,
+    pub sum_ms: f64,
+    pub count: usize,
+}
 
 struct Metrics {
     total_queries: AtomicUsize,
     failed_parses: AtomicUsize,
+    blocked_queries: AtomicUsize,
+    cache_hits: AtomicUsize,
+    cache_misses: AtomicUsize,
+    rate_limited: AtomicUsize,
+    by_qtype: Mutex<HashMap<String, usize>>,
+    resolution_latency: LatencyHistogram,
 }
 
-static METRICS: Metrics = Metrics {
+static METRICS: Lazy<Metrics> = Lazy::new(|| Metrics {
     total_queries: AtomicUsize::new(0),
     failed_parses: AtomicUsize::new(0),
-};
+    blocked_queries: AtomicUsize::new(0),
+    cache_hits: AtomicUsize::new(0),
+    cache_misses: AtomicUsize::new(0),
+    rate_limited: AtomicUsize::new(0),
+    by_qtype: Mutex::new(HashMap::new()),
+    resolution_latency: LatencyHistogram::new(),
+});
+
+/// Count a query answered with a synthesized blacklist response instead of
+/// its real answer. Called from [`crate::blacklist`] so its matching logic
+/// doesn't need to reach into `METRICS` directly.
+pub(crate) fn record_blocked_query() {
+    METRICS.blocked_queries.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count one query received by the plain UDP, DoT or DoH listener. Called
+/// before any blacklist/cache/resolution handling (over DoT, before parsing
+/// too, so parse failures are included) so `dns_queries_total` always
+/// bounds every other per-query counter from above.
+pub(crate) fn record_total_query() {
+    METRICS.total_queries.fetch_add(1, Ordering::Relaxed);
+}
+
+fn record_cache_hit() {
+    METRICS.cache_hits.fetch_add(1, Ordering::Relaxed);
+}
+
+fn record_cache_miss() {
+    METRICS.cache_misses.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a connection turned away by [`RATE_LIMITER`] before it was ever
+/// parsed as a DNS query.
+pub(crate) fn record_rate_limited() {
+    METRICS.rate_limited.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count one query by its question type (`A`, `AAAA`, `MX`, ...).
+pub(crate) fn record_query_type(qtype: QueryType) {
+    let mut by_qtype = METRICS.by_qtype.lock().unwrap();
+    *by_qtype.entry(format!("{:?}", qtype)).or_insert(0) += 1;
+}
+
+/// Record how long it took to go from picking up one query to having its
+/// response bytes ready (cache lookup, blacklist check and/or upstream
+/// resolution), excluding the time spent writing the response out.
+pub(crate) fn record_resolution_latency(elapsed: Duration) {
+    METRICS.resolution_latency.record(elapsed);
+}
 
-static CACHE: Lazy<Mutex<LruCache<String, Vec<u8>>>> = Lazy::new(|| {
+/// A point-in-time read of the query counters, for [`crate::metrics`] to
+/// render as a Prometheus exposition.
+pub(crate) struct MetricsSnapshot {
+    pub total_queries: usize,
+    pub failed_parses: usize,
+    pub blocked_queries: usize,
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    pub rate_limited: usize,
+    pub by_qtype: Vec<(String, usize)>,
+    pub resolution_latency: LatencyHistogramSnapshot,
+}
+
+pub(crate) fn metrics_snapshot() -> MetricsSnapshot {
+    let mut by_qtype: Vec<(String, usize)> = METRICS.by_qtype.lock().unwrap()
+        .iter().map(|(qtype, count)| (qtype.clone(), *count)).collect();
+    by_qtype.sort_by(|a, b| a.0.cmp(&b.0));
+
+    MetricsSnapshot {
+        total_queries: METRICS.total_queries.load(Ordering::Relaxed),
+        failed_parses: METRICS.failed_parses.load(Ordering::Relaxed),
+        blocked_queries: METRICS.blocked_queries.load(Ordering::Relaxed),
+        cache_hits: METRICS.cache_hits.load(Ordering::Relaxed),
+        cache_misses: METRICS.cache_misses.load(Ordering::Relaxed),
+        rate_limited: METRICS.rate_limited.load(Ordering::Relaxed),
+        by_qtype,
+        resolution_latency: METRICS.resolution_latency.snapshot(),
+    }
+}
+
+/// A cached response together with the bookkeeping needed to serve it with
+/// a correctly decreasing TTL: the original TTL it was stored with, and the
+/// `Instant` at which it actually expires.
+struct CacheEntry {
+    bytes: Vec<u8>,
+    ttl: u32,
+    expiry: Instant,
+}
+
+/// Cache key: (qname, qtype), so an A and an AAAA record for the same name
+/// don't collide.
+pub(crate) type CacheKey = (String, u16);
+
+static CACHE: Lazy<Mutex<LruCache<CacheKey, CacheEntry>>> = Lazy::new(|| {
     Mutex::new(LruCache::new(100))
 });
 
-static RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(|| {
+pub(crate) static RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(|| {
     let quota = Quota::per_second(NonZeroU32::new(100).unwrap());
     RateLimiter::direct(quota)
 });
 
-#[instrument]
-pub async fn run_dot_server() -> Result<(), Box<dyn std::error::Error>> {
-    let bind_addr = std::env::var("DNS_BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:853".to_string());
-    let default_ttl = std::env::var("DNS_DEFAULT_TTL")
-        .unwrap_or_else(|_| "60".to_string())
-        .parse::<u32>()?;
+/// Once remaining TTL drops below this many seconds, start serving jittered
+/// TTLs instead of the raw remaining time, so clients don't all re-query at
+/// the exact same instant when the entry finally expires.
+static CACHE_HOLD_ON_SECS: Lazy<u64> = Lazy::new(|| {
+    std::env::var("DNS_CACHE_HOLD_ON_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30)
+});
+
+/// Upper bound (in seconds) on the jitter subtracted from the remaining TTL
+/// once it drops below `CACHE_HOLD_ON_SECS`.
+static CACHE_JITTER_SECS: Lazy<u64> = Lazy::new(|| {
+    std::env::var("DNS_CACHE_JITTER_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10)
+});
+
+/// Look up `key` in the cache. Expired entries are evicted and treated as a
+/// miss. Otherwise the cached bytes are returned with the TTL field of every
+/// answer record rewritten to the remaining time (or, once that remaining
+/// time drops below the hold-on threshold, to a jittered value) so clients
+/// see a correctly decreasing TTL instead of the original fixed one. The
+/// same served TTL is returned alongside the bytes so callers that surface
+/// it elsewhere (e.g. a DoH `Cache-Control` header) stay in sync with what
+/// the answer records themselves say.
+pub(crate) fn cache_get(key: &CacheKey) -> Option<(Vec<u8>, u32)> {
+    let mut cache = CACHE.lock().unwrap();
+    let Some(entry) = cache.get(key) else {
+        record_cache_miss();
+        return None;
+    };
+    let now = Instant::now();
+    if entry.expiry <= now {
+        cache.pop(key);
+        record_cache_miss();
+        return None;
+    }
+
+    // Clamp against a clock moving backwards, which would otherwise make
+    // `remaining` briefly exceed the TTL the entry was stored with.
+    let remaining = ((entry.expiry - now).as_secs() as u32).min(entry.ttl);
+    let mut bytes = entry.bytes.clone();
+
+    let ttl_to_serve = if (remaining as u64) < *CACHE_HOLD_ON_SECS {
+        let jitter = rand::thread_rng().gen_range(0..=*CACHE_JITTER_SECS) as u32;
+        remaining.saturating_sub(jitter).max(1)
+    } else {
+        remaining
+    };
+
+    if patch_ttls(&mut bytes, ttl_to_serve).is_err() {
+        record_cache_miss();
+        return None;
+    }
+    record_cache_hit();
+    Some((bytes, ttl_to_serve))
+}
+
+/// Store `bytes` (a fully-built response with TTL `ttl`) under `key`,
+/// expiring it `ttl` seconds from now.
+pub(crate) fn cache_put(key: CacheKey, bytes: Vec<u8>, ttl: u32) {
+    let expiry = Instant::now() + Duration::from_secs(ttl as u64);
+    CACHE.lock().unwrap().put(key, CacheEntry { bytes, ttl, expiry });
+}
+
+/// Rewrite the 4-byte TTL field of every answer record in `bytes` to
+/// `new_ttl`. Each record's TTL sits right after its serialized name plus
+/// the 2-byte type and 2-byte class, so the name (and, before that, the
+/// echoed question's name) has to be walked label-by-label to find it.
+fn patch_ttls(bytes: &mut [u8], new_ttl: u32) -> Result<(), Box<dyn std::error::Error>> {
+    if bytes.len() < 12 {
+        return Err("Response too short to contain a header".into());
+    }
+
+    let ancount = u16::from_be_bytes([bytes[6], bytes[7]]) as usize;
+
+    let mut pos = skip_name(bytes, 12)?;
+    pos += 4; // QTYPE + QCLASS
+
+    for _ in 0..ancount {
+        pos = skip_name(bytes, pos)?;
+        pos += 4; // TYPE + CLASS
+
+        if pos + 4 > bytes.len() {
+            return Err("Truncated answer record".into());
+        }
+        bytes[pos..pos + 4].copy_from_slice(&new_ttl.to_be_bytes());
+        pos += 4;
+
+        if pos + 2 > bytes.len() {
+            return Err("Truncated answer record".into());
+        }
+        let rdlength = u16::from_be_bytes([bytes[pos], bytes[pos + 1]]) as usize;
+        pos += 2 + rdlength;
+    }
+
+    Ok(())
+}
+
+/// Advance past a single (uncompressed, or pointer-terminated) name starting
+/// at `pos`, returning the offset right after it.
+fn skip_name(bytes: &[u8], mut pos: usize) -> Result<usize, Box<dyn std::error::Error>> {
+    loop {
+        if pos >= bytes.len() {
+            return Err("Truncated name".into());
+        }
+        let len = bytes[pos];
+        if len & 0b1100_0000 == 0b1100_0000 {
+            return Ok(pos + 2); // Compression pointer always ends the name
+        }
+        if len == 0 {
+            return Ok(pos + 1);
+        }
+        pos += 1 + len as usize;
+    }
+}
 
+/// Load the server's TLS certificate/key and build a `ServerConfig` with the
+/// given ALPN protocols advertised. Shared by the DoT and DoH listeners so
+/// both speak for the one certificate.
+pub(crate) fn load_tls_config(alpn_protocols: Vec<Vec<u8>>) -> Result<ServerConfig, Box<dyn std::error::Error>> {
     let cert = include_bytes!("../certs/cert.pem");
     let key = include_bytes!("../certs/key.pem");
 
@@ -57,15 +325,25 @@ pub async fn run_dot_server() -> Result<(), Box<dyn std::error::Error>> {
         .ok_or("Private key not found")?;
     let key = PrivateKey(key);
 
-    let config = ServerConfig::builder()
+    let mut config = ServerConfig::builder()
         .with_safe_defaults()
         .with_no_client_auth()
         .with_single_cert(certs, key)
         .map_err(|e| format!("Error configuring certificates: {}", e))?;
+    config.alpn_protocols = alpn_protocols;
 
+    Ok(config)
+}
+
+#[instrument]
+pub async fn run_dot_server(listener: TcpListener) -> Result<(), Box<dyn std::error::Error>> {
+    let default_ttl = std::env::var("DNS_DEFAULT_TTL")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse::<u32>()?;
+
+    let config = load_tls_config(vec![])?;
     let acceptor = TlsAcceptor::from(Arc::new(config));
-    let listener = TcpListener::bind(&bind_addr).await?;
-    info!("DNS-over-TLS server started on {}", bind_addr);
+    info!("DNS-over-TLS server started on {}", listener.local_addr()?);
 
     loop {
         let (stream, peer_addr) = listener.accept().await?;
@@ -73,12 +351,13 @@ pub async fn run_dot_server() -> Result<(), Box<dyn std::error::Error>> {
 
         if RATE_LIMITER.check_one().is_err() {
             warn!(%peer_addr, "Rate limit reached");
+            record_rate_limited();
             continue;
         }
 
         let acceptor = acceptor.clone();
         tokio::spawn(async move {
-            if let Err(e) = handle_dot_connection(acceptor, stream, peer_addr).await {
+            if let Err(e) = handle_dot_connection(acceptor, stream, peer_addr, default_ttl).await {
                 error!(%peer_addr, error = %e, "Error handling DoT connection");
             }
         });
@@ -90,6 +369,7 @@ async fn handle_dot_connection(
     acceptor: TlsAcceptor,
     stream: TcpStream,
     peer_addr: std::net::SocketAddr,
+    default_ttl: u32,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut tls_stream = acceptor.accept(stream).await?;
     info!(%peer_addr, "TLS connection established");
@@ -101,7 +381,7 @@ async fn handle_dot_connection(
     let mut buf = vec![0u8; len];
     tls_stream.read_exact(&mut buf).await?;
 
-    METRICS.total_queries.fetch_add(1, Ordering::Relaxed);
+    record_total_query();
 
     let packet = match Packet::parse(&buf) {
         Ok(p) => p,
@@ -121,32 +401,184 @@ async fn handle_dot_connection(
 
     for question in &packet.questions {
         let domain = question.qname.to_string();
+        let cache_key = (domain.clone(), question.qtype as u16);
         info!(%peer_addr, %domain, "Processing DNS query");
+        record_query_type(question.qtype);
+        let query_started = Instant::now();
+
+        if blacklist::is_domain_blocked(&domain) {
+            warn!(%peer_addr, %domain, "Blocked by blacklist");
+            let response = build_blocked_response(&buf, &question.qname, question.qtype)?;
+            record_resolution_latency(query_started.elapsed());
+            tls_stream.write_all(&response).await?;
+            continue;
+        }
 
-        if let Some(response) = CACHE.lock().unwrap().get(&domain).cloned() {
+        if let Some((response, _ttl)) = cache_get(&cache_key) {
             info!(%peer_addr, %domain, "Served response from cache");
+            record_resolution_latency(query_started.elapsed());
             tls_stream.write_all(&response).await?;
             continue;
         }
 
+        let mut blocked_by_ip = false;
+        // The TTL the answer is actually cached (and served) with. Defaults
+        // to `default_ttl` for responses that carry no answer record
+        // (NXDOMAIN, NOTIMP); overwritten below with the resolver's TTL
+        // wherever an answer is built.
+        let mut response_ttl = default_ttl;
         let response = match question.qtype {
             QueryType::A => {
-                let ip = resolver::resolve_recursively(&domain)
-                    .unwrap_or_else(|| "192.168.1.1".parse().unwrap());
-                info!(%peer_addr, %domain, ip = %ip, "Resolved DNS A record");
-                build_dns_response(&buf, &question.qname, ip, 60)?
+                let resolved = resolver::resolve_recursively(&domain);
+                let ad = resolver::answer_is_secure();
+                let (ip, ttl) = resolved.unwrap_or_else(|| ("192.168.1.1".parse().unwrap(), default_ttl));
+                response_ttl = ttl;
+                if blacklist::is_ip_blocked(&ip) {
+                    warn!(%peer_addr, %domain, %ip, "Resolved IP blocked by blacklist");
+                    blocked_by_ip = true;
+                    build_blocked_response(&buf, &question.qname, question.qtype)?
+                } else {
+                    info!(%peer_addr, %domain, ip = %ip, "Resolved DNS A record");
+                    build_dns_response(&buf, &question.qname, ip, ttl, ad)?
+                }
             }
             QueryType::AAAA => {
-                let ip = "::1".parse().unwrap();
-                build_dns_response(&buf, &question.qname, ip, 60)?
+                let resolved = resolver::resolve_aaaa(&domain);
+                let ad = resolver::answer_is_secure();
+                let (ip, ttl) = resolved.unwrap_or_else(|| ("::1".parse().unwrap(), default_ttl));
+                response_ttl = ttl;
+                if blacklist::is_ip_blocked(&ip) {
+                    warn!(%peer_addr, %domain, %ip, "Resolved IP blocked by blacklist");
+                    blocked_by_ip = true;
+                    build_blocked_response(&buf, &question.qname, question.qtype)?
+                } else {
+                    build_dns_response(&buf, &question.qname, ip, ttl, ad)?
+                }
+            }
+            QueryType::NS => {
+                let (targets, ttl) = resolver::resolve_ns(&domain);
+                response_ttl = ttl;
+                let ad = resolver::answer_is_secure();
+                build_name_list_response(&buf, &question.qname, &targets, ttl, ad,
+                    |name| RData::NS(dns_parser::rdata::Ns(name)))?
+            }
+            QueryType::CNAME => match resolver::resolve_cname(&domain) {
+                Some((target, ttl)) => {
+                    response_ttl = ttl;
+                    let ad = resolver::answer_is_secure();
+                    build_name_list_response(&buf, &question.qname, &[target], ttl, ad,
+                        |name| RData::CNAME(dns_parser::rdata::Cname(name)))?
+                }
+                None => build_nxdomain_response(&buf),
+            },
+            QueryType::PTR => match resolver::resolve_ptr(&domain) {
+                resolver::PtrLookup::Found(target, ttl) => {
+                    response_ttl = ttl;
+                    let ad = resolver::answer_is_secure();
+                    build_name_list_response(&buf, &question.qname, &[target], ttl, ad,
+                        |name| RData::PTR(dns_parser::rdata::Ptr(name)))?
+                }
+                resolver::PtrLookup::NotFound => build_nxdomain_response(&buf),
+                resolver::PtrLookup::Unsupported => build_notimp_response(&buf),
+            },
+            QueryType::MX => {
+                let (mxs, ttl) = resolver::resolve_mx(&domain);
+                if mxs.is_empty() {
+                    build_nxdomain_response(&buf)
+                } else {
+                    response_ttl = ttl;
+                    let name_bufs = mxs.iter()
+                        .map(|(_, exchange)| encode_name(exchange))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let mut response = response_header(&buf, name_bufs.len() as u16, 0x80 | ad_bit(resolver::answer_is_secure()));
+                    for ((preference, _), name_buf) in mxs.iter().zip(&name_bufs) {
+                        let exchange = dns_parser::Name::scan(name_buf, name_buf)?;
+                        let record = ResourceRecord {
+                            name: question.qname.clone(),
+                            cls: Class::IN,
+                            ttl,
+                            data: RData::MX(dns_parser::rdata::Mx { preference: *preference, exchange }),
+                            multicast_unique: false,
+                        };
+                        serialize_resource_record(&record, &mut response)?;
+                    }
+                    response
+                }
+            }
+            QueryType::SRV => {
+                let (srvs, ttl) = resolver::resolve_srv(&domain);
+                if srvs.is_empty() {
+                    build_nxdomain_response(&buf)
+                } else {
+                    response_ttl = ttl;
+                    let name_bufs = srvs.iter()
+                        .map(|srv| encode_name(&srv.target))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let mut response = response_header(&buf, name_bufs.len() as u16, 0x80 | ad_bit(resolver::answer_is_secure()));
+                    for (srv, name_buf) in srvs.iter().zip(&name_bufs) {
+                        let target = dns_parser::Name::scan(name_buf, name_buf)?;
+                        let record = ResourceRecord {
+                            name: question.qname.clone(),
+                            cls: Class::IN,
+                            ttl,
+                            data: RData::SRV(dns_parser::rdata::Srv {
+                                priority: srv.priority,
+                                weight: srv.weight,
+                                port: srv.port,
+                                target,
+                            }),
+                            multicast_unique: false,
+                        };
+                        serialize_resource_record(&record, &mut response)?;
+                    }
+                    response
+                }
+            }
+            QueryType::SOA => match resolver::resolve_soa(&domain) {
+                Some((soa, ttl)) => {
+                    response_ttl = ttl;
+                    let primary_ns_buf = encode_name(&soa.primary_ns)?;
+                    let mailbox_buf = encode_name(&soa.mailbox)?;
+                    let mut response = response_header(&buf, 1, 0x80 | ad_bit(resolver::answer_is_secure()));
+                    let record = ResourceRecord {
+                        name: question.qname.clone(),
+                        cls: Class::IN,
+                        ttl,
+                        data: RData::SOA(dns_parser::rdata::Soa {
+                            primary_ns: dns_parser::Name::scan(&primary_ns_buf, &primary_ns_buf)?,
+                            mailbox: dns_parser::Name::scan(&mailbox_buf, &mailbox_buf)?,
+                            serial: soa.serial,
+                            refresh: soa.refresh,
+                            retry: soa.retry,
+                            expire: soa.expire,
+                            minimum_ttl: soa.minimum_ttl,
+                        }),
+                        multicast_unique: false,
+                    };
+                    serialize_resource_record(&record, &mut response)?;
+                    response
+                }
+                None => build_nxdomain_response(&buf),
+            },
+            QueryType::TXT => {
+                let (chunks, ttl) = resolver::resolve_txt(&domain);
+                if chunks.is_empty() {
+                    build_nxdomain_response(&buf)
+                } else {
+                    response_ttl = ttl;
+                    build_txt_response(&buf, &question.qname, &chunks, ttl, resolver::answer_is_secure())?
+                }
             }
             _ => {
-                warn!(%peer_addr, %domain, qtype = ?question.qtype, "Unsupported query type");
-                continue;
+                warn!(%peer_addr, %domain, qtype = ?question.qtype, "Unsupported query type, replying NOTIMP");
+                build_notimp_response(&buf)
             }
         };
 
-        CACHE.lock().unwrap().put(domain.clone(), response.clone());
+        record_resolution_latency(query_started.elapsed());
+        if !blocked_by_ip {
+            cache_put(cache_key, response.clone(), response_ttl);
+        }
         tls_stream.write_all(&response).await?;
         info!(%peer_addr, %domain, "Response sent");
     }
@@ -154,21 +586,40 @@ async fn handle_dot_connection(
     Ok(())
 }
 
-fn build_dns_response(
-    query: &[u8],
-    qname: &dns_parser::Name,
-    ip: IpAddr,
-    ttl: u32,
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+/// The AD (Authentic Data) bit in the low flags byte: set on an answer that
+/// passed DNSSEC validation, so a client can tell it's trustworthy. Callers
+/// decide this via [`resolver::answer_is_secure`], not just whether
+/// validation was requested.
+const FLAG_AD: u8 = 0x20;
+
+fn ad_bit(ad: bool) -> u8 {
+    if ad { FLAG_AD } else { 0 }
+}
+
+/// Build the 12-byte header plus echoed question section shared by every
+/// response, with ANCOUNT set to `ancount` and the low flags byte set to
+/// `flags_lo` (so callers can set the RCODE nibble).
+fn response_header(query: &[u8], ancount: u16, flags_lo: u8) -> Vec<u8> {
     let mut response = Vec::new();
     response.extend_from_slice(&query[..2]); // Transaction ID
     response.push(0x81); // Flags: Standard query response
-    response.push(0x80);
+    response.push(flags_lo);
     response.extend_from_slice(&query[4..6]); // QDCOUNT
-    response.extend_from_slice(b"\x00\x01"); // ANCOUNT
+    response.extend_from_slice(&ancount.to_be_bytes()); // ANCOUNT
     response.extend_from_slice(b"\x00\x00"); // NSCOUNT
     response.extend_from_slice(b"\x00\x00"); // ARCOUNT
     response.extend_from_slice(&query[12..]); // Original question
+    response
+}
+
+pub(crate) fn build_dns_response(
+    query: &[u8],
+    qname: &dns_parser::Name,
+    ip: IpAddr,
+    ttl: u32,
+    ad: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut response = response_header(query, 1, 0x80 | ad_bit(ad));
 
     let rdata = match ip {
         IpAddr::V4(ipv4) => RData::A(dns_parser::rdata::A(ipv4)),
@@ -186,3 +637,89 @@ fn build_dns_response(
     serialize_resource_record(&record, &mut response)?;
     Ok(response)
 }
+
+/// Build a response carrying one answer record per name in `targets`, each
+/// wrapped into an `RData` via `to_rdata`. Used for NS/CNAME/PTR, which all
+/// just echo back a domain name.
+fn build_name_list_response<F>(
+    query: &[u8],
+    qname: &dns_parser::Name,
+    targets: &[String],
+    ttl: u32,
+    ad: bool,
+    to_rdata: F,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>>
+where
+    F: for<'a> Fn(dns_parser::Name<'a>) -> RData<'a>,
+{
+    let name_bufs = targets.iter()
+        .map(|t| encode_name(t))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut response = response_header(query, name_bufs.len() as u16, 0x80 | ad_bit(ad));
+    for name_buf in &name_bufs {
+        let name = dns_parser::Name::scan(name_buf, name_buf)?;
+        let record = ResourceRecord {
+            name: qname.clone(),
+            cls: Class::IN,
+            ttl,
+            data: to_rdata(name),
+            multicast_unique: false,
+        };
+        serialize_resource_record(&record, &mut response)?;
+    }
+    Ok(response)
+}
+
+/// TXT answers can't go through `serialize_resource_record`: `dns_parser`
+/// only lets you build an `RData::TXT` by parsing wire bytes, so the record
+/// envelope (name/type/class/ttl/rdlength) is written by hand here instead.
+pub(crate) fn build_txt_response(
+    query: &[u8],
+    qname: &dns_parser::Name,
+    chunks: &[Vec<u8>],
+    ttl: u32,
+    ad: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut response = response_header(query, 1, 0x80 | ad_bit(ad));
+
+    response.extend_from_slice(&encode_name(&qname.to_string())?);
+    response.extend_from_slice(&16u16.to_be_bytes()); // TYPE TXT
+    response.extend_from_slice(&(Class::IN as u16).to_be_bytes());
+    response.extend_from_slice(&ttl.to_be_bytes());
+
+    let data_len_pos = response.len();
+    response.extend_from_slice(&[0, 0]);
+    let start_len = response.len();
+    for chunk in chunks {
+        response.push(chunk.len() as u8);
+        response.extend_from_slice(chunk);
+    }
+    let data_len = (response.len() - start_len) as u16;
+    response[data_len_pos..data_len_pos + 2].copy_from_slice(&data_len.to_be_bytes());
+
+    Ok(response)
+}
+
+fn build_nxdomain_response(query: &[u8]) -> Vec<u8> {
+    response_header(query, 0, 0x83) // RCODE 3 = NXDOMAIN
+}
+
+pub(crate) fn build_notimp_response(query: &[u8]) -> Vec<u8> {
+    response_header(query, 0, 0x84) // RCODE 4 = Not Implemented
+}
+
+/// Build the synthesized answer for a query the blacklist matched, per the
+/// configured [`BlockAction`].
+fn build_blocked_response(
+    query: &[u8],
+    qname: &dns_parser::Name,
+    qtype: QueryType,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match blacklist::block_action() {
+        BlockAction::NxDomain => Ok(build_nxdomain_response(query)),
+        BlockAction::Sinkhole => {
+            let ip = blacklist::sinkhole_ip(qtype == QueryType::AAAA);
+            build_dns_response(query, qname, ip, 60, false)
+        }
+    }
+}
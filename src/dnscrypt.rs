@@ -0,0 +1,246 @@
+//! DNSCrypt v2 transport: a dedicated UDP listener that speaks the DNSCrypt
+//! wire protocol directly, so this resolver can be published as a DNS stamp.
+//!
+//! Clients first fetch our signed certificate as a plain, unencrypted TXT
+//! query for `2.dnscrypt-cert.<provider-name>`; it binds our rotating
+//! short-term X25519 key pair to the long-term Ed25519 provider identity.
+//! Every other query is expected to arrive wrapped in an encrypted envelope
+//! built from an X25519 exchange between the client's ephemeral key and our
+//! current short-term key pair, with the query itself padded and sealed
+//! under XChaCha20-Poly1305.
+
+use chacha20poly1305::{XChaCha20Poly1305, Key, XNonce};
+use chacha20poly1305::aead::{Aead, NewAead};
+use dns_parser::{Packet, QueryType};
+use ed25519_dalek::{Keypair as SigningKeypair, Signer};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use rand_core::OsRng;
+use tokio::net::UdpSocket;
+use tracing::{info, warn, instrument};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use x25519_dalek::{StaticSecret, PublicKey as X25519PublicKey};
+
+use crate::resolver;
+use crate::dns_over_tls::{build_dns_response, build_txt_response};
+
+/// Crypto construction identifier for X25519 + XChaCha20-Poly1305, the only
+/// one this server implements.
+const ES_VERSION: u16 = 0x0002;
+
+/// Every DNSCrypt response is prefixed with this fixed magic, per the
+/// protocol spec.
+const RESOLVER_MAGIC: [u8; 8] = *b"r6fnvWj8";
+
+/// How long a short-term key pair / certificate stays valid before we
+/// rotate to a fresh one.
+const CERT_VALIDITY_SECS: u32 = 24 * 60 * 60;
+
+/// The provider's long-term identity: an Ed25519 key pair whose signature
+/// over a short-term X25519 public key is what clients ultimately trust.
+struct ProviderIdentity {
+    signing_keypair: SigningKeypair,
+}
+
+static PROVIDER_IDENTITY: Lazy<ProviderIdentity> = Lazy::new(|| {
+    let mut csprng = OsRng {};
+    ProviderIdentity {
+        signing_keypair: SigningKeypair::generate(&mut csprng),
+    }
+});
+
+/// The resolver's current short-term X25519 key pair plus the certificate
+/// binding it to [`PROVIDER_IDENTITY`]. Rotated by [`current_cert`] once
+/// `ts_end` has passed.
+struct ShortTermCert {
+    secret: StaticSecret,
+    public: X25519PublicKey,
+    client_magic: [u8; 8],
+    serial: u32,
+    ts_start: u32,
+    ts_end: u32,
+    signature: [u8; 64],
+}
+
+static CURRENT_CERT: Lazy<Mutex<ShortTermCert>> = Lazy::new(|| Mutex::new(generate_short_term_cert(1)));
+
+fn generate_short_term_cert(serial: u32) -> ShortTermCert {
+    let secret = StaticSecret::new(OsRng);
+    let public = X25519PublicKey::from(&secret);
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+    let ts_start = now;
+    let ts_end = now + CERT_VALIDITY_SECS;
+
+    let mut signed = Vec::with_capacity(2 + 32 + 4 + 4 + 4);
+    signed.extend_from_slice(&ES_VERSION.to_be_bytes());
+    signed.extend_from_slice(public.as_bytes());
+    signed.extend_from_slice(&serial.to_be_bytes());
+    signed.extend_from_slice(&ts_start.to_be_bytes());
+    signed.extend_from_slice(&ts_end.to_be_bytes());
+    let signature = PROVIDER_IDENTITY.signing_keypair.sign(&signed).to_bytes();
+
+    let mut client_magic = [0u8; 8];
+    client_magic.copy_from_slice(&PROVIDER_IDENTITY.signing_keypair.public.to_bytes()[..8]);
+
+    ShortTermCert { secret, public, client_magic, serial, ts_start, ts_end, signature }
+}
+
+/// Return the current short-term certificate, rotating to a freshly
+/// generated one first if it has expired.
+fn current_cert() -> std::sync::MutexGuard<'static, ShortTermCert> {
+    let mut guard = CURRENT_CERT.lock().unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+    if now >= guard.ts_end {
+        let next_serial = guard.serial + 1;
+        *guard = generate_short_term_cert(next_serial);
+        info!(serial = next_serial, "Rotated DNSCrypt short-term key pair");
+    }
+    guard
+}
+
+/// Serialize the current certificate into the binary blob DNSCrypt clients
+/// expect, chunked to fit a TXT record (255 bytes per chunk).
+fn build_cert_txt_chunks() -> Vec<Vec<u8>> {
+    let cert = current_cert();
+    let mut blob = Vec::with_capacity(4 + 2 + 2 + 64 + 32 + 8 + 4 + 4 + 4);
+    blob.extend_from_slice(b"DNSC");
+    blob.extend_from_slice(&ES_VERSION.to_be_bytes());
+    blob.extend_from_slice(&[0x00, 0x00]); // Minor version, always 0
+    blob.extend_from_slice(&cert.signature);
+    blob.extend_from_slice(cert.public.as_bytes());
+    blob.extend_from_slice(&cert.client_magic);
+    blob.extend_from_slice(&cert.serial.to_be_bytes());
+    blob.extend_from_slice(&cert.ts_start.to_be_bytes());
+    blob.extend_from_slice(&cert.ts_end.to_be_bytes());
+
+    blob.chunks(255).map(|chunk| chunk.to_vec()).collect()
+}
+
+/// Append ISO/IEC 7816-4 style padding (`0x80` then zero bytes) so `data`'s
+/// length becomes a multiple of `block_size`.
+fn pad(data: &mut Vec<u8>, block_size: usize) {
+    data.push(0x80);
+    while data.len() % block_size != 0 {
+        data.push(0x00);
+    }
+}
+
+/// Reverse [`pad`]: drop trailing zero bytes, then the `0x80` marker.
+fn unpad(mut data: Vec<u8>) -> Option<Vec<u8>> {
+    while data.last() == Some(&0x00) {
+        data.pop();
+    }
+    if data.pop() != Some(0x80) {
+        return None;
+    }
+    Some(data)
+}
+
+#[instrument]
+pub async fn run_dnscrypt_server() -> Result<(), Box<dyn std::error::Error>> {
+    let bind_addr = std::env::var("DNS_DNSCRYPT_BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:5353".to_string());
+    let provider_name = std::env::var("DNS_DNSCRYPT_PROVIDER_NAME").unwrap_or_else(|_| "example.com".to_string());
+    let cert_qname = format!("2.dnscrypt-cert.{}", provider_name);
+
+    let socket = UdpSocket::bind(&bind_addr).await?;
+    info!("DNSCrypt server started on {}", bind_addr);
+
+    let mut buf = [0u8; 4096];
+    loop {
+        let (len, peer_addr) = socket.recv_from(&mut buf).await?;
+        if let Err(e) = handle_dnscrypt_packet(&socket, &buf[..len], peer_addr, &cert_qname).await {
+            warn!(%peer_addr, error = %e, "Failed to handle DNSCrypt packet");
+        }
+    }
+}
+
+async fn handle_dnscrypt_packet(
+    socket: &UdpSocket,
+    packet: &[u8],
+    peer_addr: SocketAddr,
+    cert_qname: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(response) = try_build_cert_response(packet, cert_qname)? {
+        socket.send_to(&response, peer_addr).await?;
+        info!(%peer_addr, "Served DNSCrypt certificate");
+        return Ok(());
+    }
+
+    // 8-byte client magic + 32-byte client PK + 12-byte client nonce + at
+    // least a 16-byte Poly1305 tag.
+    if packet.len() < 8 + 32 + 12 + 16 {
+        return Ok(()); // Too short to be a DNSCrypt query; ignore
+    }
+
+    let client_pk_bytes: [u8; 32] = packet[8..40].try_into().unwrap();
+    let client_nonce: [u8; 12] = packet[40..52].try_into().unwrap();
+    let encrypted_query = &packet[52..];
+
+    let (cipher, client_magic) = {
+        let cert = current_cert();
+        let shared_secret = cert.secret.diffie_hellman(&X25519PublicKey::from(client_pk_bytes));
+        (XChaCha20Poly1305::new(Key::from_slice(shared_secret.as_bytes())), cert.client_magic)
+    };
+
+    if packet[..8] != client_magic {
+        return Ok(()); // Not a DNSCrypt packet for us; ignore
+    }
+
+    let mut query_nonce = [0u8; 24];
+    query_nonce[..12].copy_from_slice(&client_nonce);
+    let padded_query = cipher.decrypt(XNonce::from_slice(&query_nonce), encrypted_query)
+        .map_err(|_| "Failed to decrypt DNSCrypt query")?;
+    let query_bytes = unpad(padded_query).ok_or("Malformed padding in DNSCrypt query")?;
+
+    let query_packet = Packet::parse(&query_bytes)?;
+    let question = query_packet.questions.first().ok_or("DNSCrypt query has no question")?;
+    let domain = question.qname.to_string();
+
+    let resolved = resolver::resolve_recursively(&domain);
+    let ad = resolver::answer_is_secure();
+    let (ip, ttl) = resolved.unwrap_or_else(|| ("192.168.1.1".parse().unwrap(), 60));
+    info!(%peer_addr, %domain, ip = %ip, "Resolved DNSCrypt query");
+
+    let mut response_bytes = build_dns_response(&query_bytes, &question.qname, ip, ttl, ad)?;
+    pad(&mut response_bytes, 64);
+
+    let mut response_nonce = [0u8; 24];
+    response_nonce[..12].copy_from_slice(&client_nonce);
+    rand::thread_rng().fill_bytes(&mut response_nonce[12..]);
+    let ciphertext = cipher.encrypt(XNonce::from_slice(&response_nonce), response_bytes.as_ref())
+        .map_err(|_| "Failed to encrypt DNSCrypt response")?;
+
+    let mut packet_out = Vec::with_capacity(RESOLVER_MAGIC.len() + response_nonce.len() + ciphertext.len());
+    packet_out.extend_from_slice(&RESOLVER_MAGIC);
+    packet_out.extend_from_slice(&response_nonce);
+    packet_out.extend_from_slice(&ciphertext);
+    socket.send_to(&packet_out, peer_addr).await?;
+
+    Ok(())
+}
+
+/// If `packet` is a plain, unencrypted query for `cert_qname`, build the
+/// certificate TXT answer for it. This query precedes any encrypted
+/// exchange, so it is never wrapped in the DNSCrypt envelope.
+fn try_build_cert_response(
+    packet: &[u8],
+    cert_qname: &str,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let Ok(parsed) = Packet::parse(packet) else {
+        return Ok(None);
+    };
+    let Some(question) = parsed.questions.first() else {
+        return Ok(None);
+    };
+    if question.qtype != QueryType::TXT
+        || question.qname.to_string().trim_end_matches('.') != cert_qname.trim_end_matches('.')
+    {
+        return Ok(None);
+    }
+
+    let chunks = build_cert_txt_chunks();
+    Ok(Some(build_txt_response(packet, &question.qname, &chunks, 60, false)?))
+}
@@ -1,22 +1,63 @@
 mod common;
 mod server;
 mod dns_over_tls;
+mod dns_over_https;
+mod dnscrypt;
+mod blacklist;
+mod metrics;
+mod privdrop;
 mod resolver;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tokio::spawn(async {
-        if let Err(e) = server::run_dns_server().await {
+    // Bind every privileged port while still root, then drop privileges
+    // before accepting a single connection.
+    let udp_socket = tokio::net::UdpSocket::bind("0.0.0.0:53").await?;
+
+    let dot_bind_addr = std::env::var("DNS_BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:853".to_string());
+    let dot_listener = tokio::net::TcpListener::bind(&dot_bind_addr).await?;
+
+    let doh_bind_addr = std::env::var("DNS_DOH_BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:443".to_string());
+    let doh_listener = tokio::net::TcpListener::bind(&doh_bind_addr).await?;
+
+    privdrop::drop_privileges()?;
+
+    tokio::spawn(async move {
+        if let Err(e) = server::run_dns_server(udp_socket).await {
             eprintln!("Error en el servidor DNS básico: {}", e);
         }
     });
 
-    tokio::spawn(async {
-        if let Err(e) = dns_over_tls::run_dot_server().await {
+    tokio::spawn(async move {
+        if let Err(e) = dns_over_tls::run_dot_server(dot_listener).await {
             eprintln!("Error en el servidor DNS-over-TLS: {}", e);
         }
     });
 
+    tokio::spawn(async move {
+        if let Err(e) = dns_over_https::run_doh_server(doh_listener).await {
+            eprintln!("Error en el servidor DNS-over-HTTPS: {}", e);
+        }
+    });
+
+    tokio::spawn(async {
+        if let Err(e) = dnscrypt::run_dnscrypt_server().await {
+            eprintln!("Error en el servidor DNSCrypt: {}", e);
+        }
+    });
+
+    tokio::spawn(async {
+        if let Err(e) = blacklist::run_blacklist_reloader().await {
+            eprintln!("Error en el recargador de la lista negra: {}", e);
+        }
+    });
+
+    tokio::spawn(async {
+        if let Err(e) = metrics::run_metrics_server().await {
+            eprintln!("Error en el servidor de métricas: {}", e);
+        }
+    });
+
     println!("Servidores DNS iniciados. Presiona Ctrl+C para salir.");
     tokio::signal::ctrl_c().await?;
     println!("Apagando servidores...");
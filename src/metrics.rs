@@ -0,0 +1,100 @@
+//! Plain-HTTP Prometheus exposition endpoint for the counters tracked in
+//! [`crate::dns_over_tls`]. Unlike the DoH listener this is deliberately not
+//! behind TLS, matching the usual "scrape me from inside the cluster" setup.
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tracing::{info, instrument};
+
+use crate::dns_over_tls::metrics_snapshot;
+
+#[instrument]
+pub async fn run_metrics_server() -> Result<(), Box<dyn std::error::Error>> {
+    let bind_addr: SocketAddr = std::env::var("DNS_METRICS_BIND_ADDR")
+        .unwrap_or_else(|_| "0.0.0.0:9100".to_string())
+        .parse()?;
+
+    let make_svc = make_service_fn(|_conn| async {
+        Ok::<_, Infallible>(service_fn(handle_metrics_request))
+    });
+
+    info!("Metrics server started on {}", bind_addr);
+    Server::bind(&bind_addr).serve(make_svc).await?;
+    Ok(())
+}
+
+async fn handle_metrics_request(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.uri().path() != "/metrics" {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(Body::from(render_metrics()))
+        .unwrap())
+}
+
+fn render_metrics() -> String {
+    let snapshot = metrics_snapshot();
+    let mut out = format!(
+        "# HELP dns_queries_total Total DNS queries received over plain UDP, DNS-over-TLS and DNS-over-HTTPS.\n\
+         # TYPE dns_queries_total counter\n\
+         dns_queries_total {total}\n\
+         # HELP dns_parse_failures_total Queries that failed to parse as a DNS packet.\n\
+         # TYPE dns_parse_failures_total counter\n\
+         dns_parse_failures_total {failed}\n\
+         # HELP dns_blocked_queries_total Queries answered with a synthesized blacklist response.\n\
+         # TYPE dns_blocked_queries_total counter\n\
+         dns_blocked_queries_total {blocked}\n\
+         # HELP dns_rate_limited_total Connections turned away by the rate limiter.\n\
+         # TYPE dns_rate_limited_total counter\n\
+         dns_rate_limited_total {rate_limited}\n\
+         # HELP dns_cache_hits_total Queries served from the response cache.\n\
+         # TYPE dns_cache_hits_total counter\n\
+         dns_cache_hits_total {cache_hits}\n\
+         # HELP dns_cache_misses_total Queries not found in the response cache.\n\
+         # TYPE dns_cache_misses_total counter\n\
+         dns_cache_misses_total {cache_misses}\n",
+        total = snapshot.total_queries,
+        failed = snapshot.failed_parses,
+        blocked = snapshot.blocked_queries,
+        rate_limited = snapshot.rate_limited,
+        cache_hits = snapshot.cache_hits,
+        cache_misses = snapshot.cache_misses,
+    );
+
+    out.push_str(
+        "# HELP dns_queries_by_type_total Queries received, broken down by question type.\n\
+         # TYPE dns_queries_by_type_total counter\n",
+    );
+    for (qtype, count) in &snapshot.by_qtype {
+        out.push_str(&format!("dns_queries_by_type_total{{qtype=\"{qtype}\"}} {count}\n"));
+    }
+
+    out.push_str(
+        "# HELP dns_resolution_latency_seconds Time from picking up a query to having its response bytes ready.\n\
+         # TYPE dns_resolution_latency_seconds histogram\n",
+    );
+    let histogram = &snapshot.resolution_latency;
+    for (bound_ms, count) in &histogram.buckets {
+        out.push_str(&format!(
+            "dns_resolution_latency_seconds_bucket{{le=\"{le}\"}} {count}\n",
+            le = bound_ms / 1000.0,
+        ));
+    }
+    out.push_str(&format!(
+        "dns_resolution_latency_seconds_bucket{{le=\"+Inf\"}} {count}\n\
+         dns_resolution_latency_seconds_sum {sum}\n\
+         dns_resolution_latency_seconds_count {count}\n",
+        count = histogram.count,
+        sum = histogram.sum_ms / 1000.0,
+    ));
+
+    out
+}
@@ -0,0 +1,35 @@
+//! Drop root privileges once every privileged port is bound.
+//!
+//! Binding `0.0.0.0:53`, `0.0.0.0:853`, and `0.0.0.0:443` requires root, but
+//! nothing past that point does. [`drop_privileges`] runs right after those
+//! binds succeed and before any connection is accepted, so the daemon spends
+//! as little time as possible actually running as root. This mirrors the
+//! privdrop step used by other encrypted-DNS servers.
+
+use privdrop::PrivDrop;
+use tracing::info;
+
+/// Chroot into `DNS_CHROOT` (if set), then drop from root to `DNS_USER`/
+/// `DNS_GROUP`, and confirm root can't be regained afterwards.
+///
+/// Does nothing if `DNS_USER` isn't set, so the binary still runs as-is in
+/// environments (containers, local runs) that are already unprivileged.
+pub fn drop_privileges() -> Result<(), Box<dyn std::error::Error>> {
+    let Ok(user) = std::env::var("DNS_USER") else {
+        return Ok(());
+    };
+    let group = std::env::var("DNS_GROUP").unwrap_or_else(|_| user.clone());
+
+    let mut privdrop = PrivDrop::default().user(&user).group(&group);
+    if let Ok(chroot_dir) = std::env::var("DNS_CHROOT") {
+        privdrop = privdrop.chroot(&chroot_dir);
+    }
+    privdrop.apply()?;
+
+    if unsafe { libc::setuid(0) } == 0 {
+        return Err("Privilege drop failed: still able to regain root".into());
+    }
+
+    info!(%user, %group, "Dropped root privileges");
+    Ok(())
+}
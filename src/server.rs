@@ -1,12 +1,14 @@
 use dns_parser::{Packet, RData, ResourceRecord, Class};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Instant;
 use tokio::net::UdpSocket;
 use crate::resolver;
+use crate::blacklist::{self, BlockAction};
 use crate::common::serialize_resource_record;
+use crate::dns_over_tls::{record_query_type, record_resolution_latency, record_total_query};
 
-pub async fn run_dns_server() -> Result<(), Box<dyn std::error::Error>> {
-    let socket = UdpSocket::bind("0.0.0.0:53").await?;
-    println!("Servidor DNS básico iniciado en 0.0.0.0:53");
+pub async fn run_dns_server(socket: UdpSocket) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Servidor DNS básico iniciado en {}", socket.local_addr()?);
 
     let mut buf = [0u8; 512];
 
@@ -16,39 +18,84 @@ pub async fn run_dns_server() -> Result<(), Box<dyn std::error::Error>> {
 
         if let Ok(packet) = Packet::parse(query) {
             println!("Consulta DNS recibida desde {:?}: {:?}", src, packet);
-
-            let mut response = Vec::new();
-            response.extend_from_slice(&query[..2]); // ID de la consulta
-            response.push(0x81); // Flags: Respuesta estándar
-            response.push(0x80);
-            response.extend_from_slice(&query[4..6]); // QDCOUNT
-            response.extend_from_slice(b"\x00\x01"); // ANCOUNT (1 respuesta)
-            response.extend_from_slice(b"\x00\x00"); // NSCOUNT
-            response.extend_from_slice(b"\x00\x00"); // ARCOUNT
-
-            response.extend_from_slice(&query[12..]);
+            record_total_query();
+            record_query_type(packet.questions[0].qtype);
+            let query_started = Instant::now();
 
             // Resolver recursivamente o usar una IP fija
             let domain = packet.questions[0].qname.to_string();
-            let ip = resolver::resolve_recursively(&domain).unwrap_or_else(|| "192.168.1.1".parse().unwrap());
+            let mut blocked = blacklist::is_domain_blocked(&domain);
 
-            let rdata = match ip {
-                IpAddr::V4(ipv4) => RData::A(dns_parser::rdata::A(ipv4)),
-                IpAddr::V6(ipv6) => RData::AAAA(dns_parser::rdata::Aaaa(ipv6)),
+            let ip = if blocked {
+                Ipv4Addr::UNSPECIFIED.into()
+            } else {
+                let (resolved, _ttl) = resolver::resolve_recursively(&domain).unwrap_or_else(|| ("192.168.1.1".parse().unwrap(), 60));
+                if blacklist::is_ip_blocked(&resolved) {
+                    blocked = true;
+                }
+                resolved
             };
 
-            let record = ResourceRecord {
-                name: packet.questions[0].qname.clone(),
-                cls: Class::IN, // Clase IN (Internet)
-                ttl: 60,       // TTL
-                data: rdata,
-                multicast_unique: false, // Campo multicast_unique
-            };
+            if blocked {
+                println!("Consulta bloqueada por la lista negra: {}", domain);
+            }
 
-            // Serializar manualmente el registro DNS
-            serialize_resource_record(&record, &mut response)?;
+            let response = if blocked && matches!(blacklist::block_action(), BlockAction::NxDomain) {
+                build_nxdomain_response(query)
+            } else {
+                let ip = if blocked { blacklist::sinkhole_ip(matches!(ip, IpAddr::V6(_))) } else { ip };
+                build_answer_response(query, &packet.questions[0].qname, ip)?
+            };
 
+            record_resolution_latency(query_started.elapsed());
             socket.send_to(&response, src).await?;
         }
     }
 }
+
+fn build_answer_response(
+    query: &[u8],
+    qname: &dns_parser::Name,
+    ip: IpAddr,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut response = Vec::new();
+    response.extend_from_slice(&query[..2]); // ID de la consulta
+    response.push(0x81); // Flags: Respuesta estándar
+    response.push(0x80);
+    response.extend_from_slice(&query[4..6]); // QDCOUNT
+    response.extend_from_slice(b"\x00\x01"); // ANCOUNT (1 respuesta)
+    response.extend_from_slice(b"\x00\x00"); // NSCOUNT
+    response.extend_from_slice(b"\x00\x00"); // ARCOUNT
+
+    response.extend_from_slice(&query[12..]);
+
+    let rdata = match ip {
+        IpAddr::V4(ipv4) => RData::A(dns_parser::rdata::A(ipv4)),
+        IpAddr::V6(ipv6) => RData::AAAA(dns_parser::rdata::Aaaa(ipv6)),
+    };
+
+    let record = ResourceRecord {
+        name: qname.clone(),
+        cls: Class::IN, // Clase IN (Internet)
+        ttl: 60,       // TTL
+        data: rdata,
+        multicast_unique: false, // Campo multicast_unique
+    };
+
+    // Serializar manualmente el registro DNS
+    serialize_resource_record(&record, &mut response)?;
+    Ok(response)
+}
+
+fn build_nxdomain_response(query: &[u8]) -> Vec<u8> {
+    let mut response = Vec::new();
+    response.extend_from_slice(&query[..2]);
+    response.push(0x81);
+    response.push(0x83); // RCODE 3 = NXDOMAIN
+    response.extend_from_slice(&query[4..6]);
+    response.extend_from_slice(b"\x00\x00"); // ANCOUNT
+    response.extend_from_slice(b"\x00\x00");
+    response.extend_from_slice(b"\x00\x00");
+    response.extend_from_slice(&query[12..]);
+    response
+}